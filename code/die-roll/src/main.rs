@@ -1,53 +1,333 @@
 // In main.rs
 use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{Error, Read};
+use std::io::{Error, ErrorKind, Read};
 
-// Slapdash code to grab a random u32 value.
-fn get_random_int() -> Result<u32, Error> {
-    let urandom = File::open("/dev/urandom")?;
-    // Take gives us a handle that, when read, gives us only n bytes. 1 in this case.
-    let mut handle = urandom.take(1);
-    let mut buf = [0_u8; 4];
-    handle.read(&mut buf)?;
-    // Snip off all but the last three bits.
-    // I won't lie that it took me a few guesses for which bits to snip.
-    // Endianness is hard.
-    buf[0] &= 0b00000111;
-    // Rust hackery to turn four u8s into a u32.
-    let res = u32::from_le_bytes(buf);
-    Ok(res)
-}
-
-// This is just our die roll as a function.
-fn die_roll(random_int: u32) -> u32 {
-    random_int % 6 + 1
-}
-
-// Run some specified number of trials of our die roll experiment,
-// keeping the frequency of our results in a map.
-fn get_longrunning_frequency(trials: u32) -> Result<BTreeMap<u32, u32>, Error> {
+#[cfg(feature = "rand")]
+use rand::Rng;
+#[cfg(feature = "rand")]
+use rand_core::{CryptoRng, RngCore, SeedableRng};
+
+// Wraps a `Read` and hands out random u32s without re-opening or re-reading
+// the underlying source on every call. It keeps an internal buffer of raw
+// bytes and refills it in large blocks whenever it runs dry, so a long
+// trial run costs a handful of syscalls instead of one per roll.
+struct ReaderRng<R: Read> {
+    reader: R,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+// Size of each refill read. Large enough that 10,000 trials (40,000 bytes)
+// only needs a handful of refills instead of thousands of one-byte reads.
+const BUFFER_SIZE: usize = 4096;
+
+impl<R: Read> ReaderRng<R> {
+    fn new(reader: R) -> Self {
+        ReaderRng {
+            reader,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    // Top the buffer back up from the underlying reader. Loops on `read`
+    // rather than demanding a full `BUFFER_SIZE` block via `read_exact`, so
+    // a reader that has fewer than `BUFFER_SIZE` bytes left (e.g. a `Cursor`
+    // of canned test bytes) still fills the buffer with whatever it has.
+    fn refill(&mut self) -> Result<(), Error> {
+        let mut block = vec![0_u8; BUFFER_SIZE];
+        let mut filled = 0;
+        while filled < block.len() {
+            match self.reader.read(&mut block[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        block.truncate(filled);
+        self.buf = block;
+        self.pos = 0;
+        Ok(())
+    }
+
+    fn next_u32(&mut self) -> Result<u32, Error> {
+        if self.pos + 4 > self.buf.len() {
+            self.refill()?;
+            if self.buf.len() < 4 {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "entropy source exhausted",
+                ));
+            }
+        }
+        let mut bytes = [0_u8; 4];
+        bytes.copy_from_slice(&self.buf[self.pos..self.pos + 4]);
+        self.pos += 4;
+        Ok(u32::from_le_bytes(bytes))
+    }
+}
+
+// Lets `ReaderRng` plug into the `rand` ecosystem: `die_roll`/`roll` can be
+// expressed with `rng.gen_range(..)`, and a `ReaderRng` can stand in for any
+// other `rand_core::RngCore` (e.g. a seeded PRNG) in code that's generic
+// over `Rng`. `next_u32` panics on a read failure since `RngCore` has no
+// room for a `Result` — the panic matches how this trait's other
+// implementors treat a dead entropy source.
+#[cfg(feature = "rand")]
+impl<R: Read> RngCore for ReaderRng<R> {
+    fn next_u32(&mut self) -> u32 {
+        ReaderRng::next_u32(self).expect("failed to read from entropy source")
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rand_core::impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// `/dev/urandom` is a CSPRNG, so a `ReaderRng<File>` is safe to use anywhere
+// a `CryptoRng` is required. This is not true of `ReaderRng` in general
+// (e.g. `ReaderRng<SeedStream>` below), so the marker is only given to the
+// `File`-backed instantiation.
+#[cfg(feature = "rand")]
+impl CryptoRng for ReaderRng<File> {}
+
+// A small 64-bit LCG byte stream so `ReaderRng` can be seeded with a
+// fixed value instead of reading `/dev/urandom`, giving reproducible rolls
+// for tests or replaying a specific run.
+#[cfg(feature = "rand")]
+struct SeedStream {
+    state: u64,
+}
+
+#[cfg(feature = "rand")]
+impl Read for SeedStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        for byte in buf.iter_mut() {
+            self.state = self
+                .state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            *byte = (self.state >> 56) as u8;
+        }
+        Ok(buf.len())
+    }
+}
+
+#[cfg(feature = "rand")]
+impl SeedableRng for ReaderRng<SeedStream> {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        ReaderRng::new(SeedStream {
+            state: u64::from_le_bytes(seed),
+        })
+    }
+}
+
+// Roll an n-sided die off of `rng`, using rejection sampling so every face
+// comes up with equal probability. Masking a sampled value down to a small
+// range (as the old `random_int % 6` did) is biased whenever `sides` doesn't
+// evenly divide the range of values we can draw, so instead we find the
+// largest multiple of `sides` that fits in a u32 and throw away any draw
+// that lands at or above it before reducing mod `sides`.
+fn roll<R: Read>(rng: &mut ReaderRng<R>, sides: u32) -> Result<u32, Error> {
+    let range = u64::from(u32::MAX) + 1;
+    let limit = range - (range % u64::from(sides));
+    loop {
+        let value = u64::from(rng.next_u32()?);
+        if value < limit {
+            return Ok((value % u64::from(sides)) as u32 + 1);
+        }
+    }
+}
+
+// Same die roll, but expressed against `rand`'s `Rng` extension trait
+// instead of hand-rolled rejection sampling. Works with any `rand_core::RngCore`
+// implementor, not just `ReaderRng` — a `ReaderRng<File>` for OS entropy, or
+// a seeded `ReaderRng<SeedStream>` / other `SeedableRng` for reproducible
+// runs.
+#[cfg(feature = "rand")]
+fn roll_with_rng<T: Rng>(rng: &mut T, sides: u32) -> u32 {
+    rng.gen_range(1..=sides)
+}
+
+// Run some specified number of trials of our die roll experiment, returning
+// both the flat sequence of rolls (for percentiles) and the frequency of
+// each result (for the histogram and chi-square report).
+fn get_longrunning_frequency<R: Read>(
+    trials: u32,
+    sides: u32,
+    rng: &mut ReaderRng<R>,
+) -> Result<(Vec<u32>, BTreeMap<u32, u32>), Error> {
+    let mut rolls: Vec<u32> = Vec::with_capacity(trials as usize);
     let mut frequency: BTreeMap<u32, u32> = BTreeMap::new();
     for _ in 0..trials {
-        let number = get_random_int()?;
-        let roll = die_roll(number);
+        let value = roll(rng, sides)?;
+        rolls.push(value);
         // Little weird rust hack, if the entry for a value doesn't exist, insert 0.
-        let counter = frequency.entry(roll).or_insert(0);
+        let counter = frequency.entry(value).or_insert(0);
         *counter += 1;
     }
-    Ok(frequency)
+    Ok((rolls, frequency))
+}
+
+// Computes chi-square goodness-of-fit critical values for p = 0.05, indexed
+// by degrees of freedom. Falls back to the Wilson-Hilferty approximation
+// past the end of the table rather than failing for large dice.
+fn chi_square_critical_value(dof: u32) -> f64 {
+    const TABLE: [f64; 30] = [
+        3.841, 5.991, 7.815, 9.488, 11.070, 12.592, 14.067, 15.507, 16.919, 18.307, 19.675,
+        21.026, 22.362, 23.685, 24.996, 26.296, 27.587, 28.869, 30.144, 31.410, 32.671, 33.924,
+        35.172, 36.415, 37.652, 38.885, 40.113, 41.337, 42.557, 43.773,
+    ];
+    if let Some(&value) = TABLE.get((dof - 1) as usize) {
+        return value;
+    }
+    let d = f64::from(dof);
+    let z = 1.645_f64;
+    d * (1.0 - 2.0 / (9.0 * d) + z * (2.0 / (9.0 * d)).sqrt()).powi(3)
+}
+
+// χ² = Σ (observed − expected)² / expected, comparing the observed roll
+// frequencies against the uniform distribution every face should follow.
+fn chi_square_statistic(frequency: &BTreeMap<u32, u32>, trials: u32, sides: u32) -> f64 {
+    let expected = f64::from(trials) / f64::from(sides);
+    (1..=sides)
+        .map(|face| {
+            let observed = f64::from(*frequency.get(&face).unwrap_or(&0));
+            (observed - expected).powi(2) / expected
+        })
+        .sum()
+}
+
+// Renders the observed counts as a row of proportional `#` bars, one row
+// per face, scaled so the most frequent face fills `max_bar_width`.
+fn histogram(frequency: &BTreeMap<u32, u32>, max_bar_width: usize) -> String {
+    let max_count = frequency.values().copied().max().unwrap_or(0);
+    let mut out = String::new();
+    for (value, count) in frequency {
+        let bar_width = if max_count == 0 {
+            0
+        } else {
+            (f64::from(*count) / f64::from(max_count) * max_bar_width as f64).round() as usize
+        };
+        out.push_str(&format!(
+            "{:>3} | {} {}\n",
+            value,
+            "#".repeat(bar_width),
+            count
+        ));
+    }
+    out
+}
+
+// Given the flat sequence of rolls and a list of percentile ranks (e.g.
+// [50, 90, 99]), returns the roll value at each rank, keyed as "p50", "p90",
+// etc. Ranks are located in the sorted samples between the observed min and
+// max.
+fn percentiles(rolls: &[u32], ranks: &[u32]) -> BTreeMap<String, f64> {
+    let mut sorted = rolls.to_vec();
+    sorted.sort_unstable();
+    let mut result = BTreeMap::new();
+    if sorted.is_empty() {
+        return result;
+    }
+    for &rank in ranks {
+        let index = ((f64::from(rank) / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+        result.insert(format!("p{}", rank), f64::from(sorted[index]));
+    }
+    result
 }
 
 fn main() -> Result<(), Error> {
     let trials = 10_000;
-    let frequency = get_longrunning_frequency(trials)?;
-    for (value, appearances) in frequency {
+    let sides = 6;
+    let urandom = File::open("/dev/urandom")?;
+    let mut rng = ReaderRng::new(urandom);
+    let (rolls, frequency) = get_longrunning_frequency(trials, sides, &mut rng)?;
+    for (value, appearances) in &frequency {
         println!(
             "Value: {}, frequency: {}, percentage: {}%",
             value,
             appearances,
-            100.0 * appearances as f64 / trials as f64
+            100.0 * *appearances as f64 / trials as f64
         );
     }
+
+    let statistic = chi_square_statistic(&frequency, trials, sides);
+    let dof = sides - 1;
+    let critical = chi_square_critical_value(dof);
+    let verdict = if statistic <= critical { "fair" } else { "biased" };
+    println!(
+        "chi-square: {:.3} (critical value at dof={}, p=0.05: {:.3}) -> {}",
+        statistic, dof, critical, verdict
+    );
+
+    print!("{}", histogram(&frequency, 50));
+
+    for (label, value) in percentiles(&rolls, &[50, 90, 99]) {
+        println!("{}: {}", label, value);
+    }
+
+    #[cfg(feature = "rand")]
+    println!(
+        "(rand feature) one more roll via gen_range: {}",
+        roll_with_rng(&mut rng, sides)
+    );
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A deterministic, effectively-infinite byte source for tests so we can
+    // run enough trials to check the roll distribution without touching
+    // real entropy. Not random in any cryptographic sense, just well-mixed.
+    struct LcgReader {
+        state: u64,
+    }
+
+    impl Read for LcgReader {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            for byte in buf.iter_mut() {
+                self.state = self.state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                *byte = (self.state >> 56) as u8;
+            }
+            Ok(buf.len())
+        }
+    }
+
+    #[test]
+    fn roll_frequencies_are_close_to_uniform() {
+        let sides = 6;
+        let trials = 60_000;
+        let mut rng = ReaderRng::new(LcgReader { state: 42 });
+        let (_, frequency) = get_longrunning_frequency(trials, sides, &mut rng).unwrap();
+
+        let expected = trials as f64 / sides as f64;
+        let tolerance = expected * 0.05;
+        for value in 1..=sides {
+            let count = *frequency.get(&value).unwrap_or(&0) as f64;
+            assert!(
+                (count - expected).abs() < tolerance,
+                "face {} had count {}, expected close to {}",
+                value,
+                count,
+                expected
+            );
+        }
+    }
+}